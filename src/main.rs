@@ -1,49 +1,128 @@
 use regex::Regex;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
 use std::fs;
+use std::io::{self, Read};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Need path of input file as only argument");
+    let mut args: Vec<String> = env::args().collect();
+    let undirected = args.iter().any(|arg| arg == "--undirected");
+    args.retain(|arg| arg != "--undirected");
+
+    if args.len() < 2 || args.len() > 3 {
+        println!("Usage: station_routing [--undirected] <graph-file> [queries-file]");
+        println!("Queries are read from stdin if [queries-file] is omitted.");
         return;
     }
     let filename = &args[1];
     let input = fs::read_to_string(filename).expect("Unable to read input file");
-    let routes = RouteMap::new(&input);
+    let format = InputFormat::detect(filename, &input);
+    let routes = RouteMap::parse(&input, format, undirected);
+
+    let queries = match args.get(2) {
+        Some(queries_file) => fs::read_to_string(queries_file).expect("Unable to read queries file"),
+        None => {
+            let mut buffer = String::new();
+            io::stdin()
+                .read_to_string(&mut buffer)
+                .expect("Unable to read queries from stdin");
+            buffer
+        }
+    };
 
-    for (index, cmd) in COMMANDS.iter().enumerate() {
-        println!("Output #{}: {}", index + 1, routes.eval_cmd(cmd));
+    for (index, line) in queries
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+    {
+        let output = match Query::parse(line) {
+            Ok(query) => routes.eval_cmd(&query),
+            Err(err) => err,
+        };
+        println!("Output #{}: {}", index + 1, output);
     }
 }
 
-#[derive(Debug)]
-enum Command {
-    ABCDistance,
-    ADDistance,
-    ADCDistance,
-    AEBCDDistance,
-    AEDDistance,
-    CCircular,
-    ACFourStop,
-    ACExpress,
-    BCircle,
-    CLessThanCircular,
-}
-
-static COMMANDS: [Command; 10] = [
-    Command::ABCDistance,
-    Command::ADDistance,
-    Command::ADCDistance,
-    Command::AEBCDDistance,
-    Command::AEDDistance,
-    Command::CCircular,
-    Command::ACFourStop,
-    Command::ACExpress,
-    Command::BCircle,
-    Command::CLessThanCircular,
-];
+/// A parsed query line. Supported grammar:
+///   dist A-B-C              -> distance travelling straight through A, B, C
+///   shortest A C            -> shortest route from A to C
+///   trips A B exact=4       -> routes from A to B with exactly 4 stops in between (5 legs)
+///   trips A B maxstops=4    -> routes from A to B of 1 to 4 legs
+///   trips C C maxdist=30    -> routes from C to C under a total distance
+///   circular C stops<=3     -> routes from C back to C within a stop count
+///   longest A C             -> longest simple route from A to C
+///   longest A C hops<=6     -> longest simple route, bounded to 6 hops
+///   tour A                  -> shortest route visiting every station, starting at A
+///   tour A closed           -> as above, but returning to A at the end
+#[derive(Debug, PartialEq)]
+enum Query {
+    Distance(Vec<String>),
+    Shortest(String, String),
+    TripsExact(String, String, u32),
+    TripsMaxStops(String, String, u32),
+    TripsMaxDistance(String, String, u32),
+    Circular(String, u32),
+    Longest(String, String, Option<u32>),
+    Tour(String, bool),
+}
+
+impl Query {
+    fn parse(line: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["dist", stops] => Ok(Query::Distance(stops.split('-').map(str::to_owned).collect())),
+            ["shortest", start, destination] => {
+                Ok(Query::Shortest((*start).to_owned(), (*destination).to_owned()))
+            }
+            ["trips", start, destination, condition] => {
+                let (key, value) = condition
+                    .split_once('=')
+                    .ok_or_else(|| format!("Malformed trips condition: {condition}"))?;
+                let value: u32 = value
+                    .parse()
+                    .map_err(|_| format!("Expected a number in: {condition}"))?;
+                let start = (*start).to_owned();
+                let destination = (*destination).to_owned();
+                match key {
+                    "exact" => Ok(Query::TripsExact(start, destination, value)),
+                    "maxstops" => Ok(Query::TripsMaxStops(start, destination, value)),
+                    "maxdist" => Ok(Query::TripsMaxDistance(start, destination, value)),
+                    _ => Err(format!("Unknown trips condition: {key}")),
+                }
+            }
+            ["circular", start, condition] => {
+                let max_stops = condition
+                    .strip_prefix("stops<=")
+                    .ok_or_else(|| format!("Malformed circular condition: {condition}"))?;
+                let max_stops: u32 = max_stops
+                    .parse()
+                    .map_err(|_| format!("Expected a number in: {condition}"))?;
+                Ok(Query::Circular((*start).to_owned(), max_stops))
+            }
+            ["longest", start, destination] => {
+                Ok(Query::Longest((*start).to_owned(), (*destination).to_owned(), None))
+            }
+            ["longest", start, destination, condition] => {
+                let hop_limit = condition
+                    .strip_prefix("hops<=")
+                    .ok_or_else(|| format!("Malformed longest condition: {condition}"))?;
+                let hop_limit: u32 = hop_limit
+                    .parse()
+                    .map_err(|_| format!("Expected a number in: {condition}"))?;
+                Ok(Query::Longest(
+                    (*start).to_owned(),
+                    (*destination).to_owned(),
+                    Some(hop_limit),
+                ))
+            }
+            ["tour", origin] => Ok(Query::Tour((*origin).to_owned(), false)),
+            ["tour", origin, "closed"] => Ok(Query::Tour((*origin).to_owned(), true)),
+            _ => Err(format!("Unrecognized query: {line}")),
+        }
+    }
+}
 
 #[derive(Debug)]
 struct Route {
@@ -54,6 +133,11 @@ type Stations = HashMap<String, HashMap<String, u32>>;
 #[derive(Debug)]
 struct RouteMap {
     graph: Stations,
+    /// Station name -> row/column index into `distance_matrix`.
+    distance_index: HashMap<String, usize>,
+    /// All-pairs shortest distances, precomputed with Floyd-Warshall.
+    /// `u32::MAX` marks an unreachable pair.
+    distance_matrix: Vec<Vec<u32>>,
 }
 
 impl Route {
@@ -64,37 +148,163 @@ impl Route {
     }
 }
 
-/// Input as list in form of `XYD, ` where X = starting point, Y = destination, D = distance
-/// Places as single a-Z char, distance as digits
+/// Which grammar `RouteMap::parse` should read the input as.
+enum InputFormat {
+    /// The original compact `XYD, ` form: X = starting point, Y =
+    /// destination, D = distance, places as a single a-Z char.
+    Compact,
+    /// Whitespace- or comma-separated columns of `From To Distance`, one
+    /// edge per line, with multi-character station names. An optional
+    /// header row is tolerated and skipped.
+    Columns,
+}
+
+impl InputFormat {
+    /// Picks `Columns` for a `.csv`/`.tsv` file, or when the first line
+    /// looks like a `From To Distance` header; `Compact` otherwise.
+    fn detect(filename: &str, input: &str) -> Self {
+        let columnar_extension = filename.ends_with(".csv") || filename.ends_with(".tsv");
+        let header_row = input
+            .lines()
+            .next()
+            .map(|line| {
+                let line = line.to_lowercase();
+                line.contains("from") && line.contains("to") && line.contains("dist")
+            })
+            .unwrap_or(false);
+        if columnar_extension || header_row {
+            InputFormat::Columns
+        } else {
+            InputFormat::Compact
+        }
+    }
+}
+
 impl RouteMap {
-    pub fn new(input: &str) -> Self {
-        let pattern = Regex::new(r"([a-zA-Z])([a-zA-Z])(\d+)").expect("invalid input regex");
+    /// Builds a `RouteMap` from `input` in the given `format`. When
+    /// `undirected` is true, every parsed edge also inserts its reverse with
+    /// the same weight.
+    pub fn parse(input: &str, format: InputFormat, undirected: bool) -> Self {
         let mut graph: Stations = HashMap::new();
+        match format {
+            InputFormat::Compact => Self::parse_compact(input, undirected, &mut graph),
+            InputFormat::Columns => Self::parse_columns(input, undirected, &mut graph),
+        }
+        let (distance_index, distance_matrix) = Self::floyd_warshall(&graph);
+        Self {
+            graph,
+            distance_index,
+            distance_matrix,
+        }
+    }
+
+    fn parse_compact(input: &str, undirected: bool, graph: &mut Stations) {
+        let pattern = Regex::new(r"([a-zA-Z])([a-zA-Z])(\d+)").expect("invalid input regex");
         for route in pattern.captures_iter(input) {
             let start = &route[1];
             let destination = &route[2];
             let distance: u32 = route[3].parse().expect("Expecting u32 as distance");
-            if !graph.contains_key(start) {
-                graph.insert(start.to_owned(), HashMap::new());
+            Self::insert_edge(graph, start, destination, distance, undirected);
+        }
+    }
+
+    fn parse_columns(input: &str, undirected: bool, graph: &mut Stations) {
+        for line in input.lines() {
+            let columns: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .map(str::trim)
+                .filter(|column| !column.is_empty())
+                .collect();
+            let [start, destination, distance] = columns.as_slice() else {
+                continue;
+            };
+            // A header row's distance column won't parse as a number.
+            let Ok(distance) = distance.parse::<u32>() else {
+                continue;
             };
-            let station = graph.get_mut(start).expect("Station was not present");
-            station.insert(destination.to_owned(), distance);
+            Self::insert_edge(graph, start, destination, distance, undirected);
+        }
+    }
+
+    fn insert_edge(graph: &mut Stations, start: &str, destination: &str, distance: u32, undirected: bool) {
+        graph
+            .entry(start.to_owned())
+            .or_default()
+            .insert(destination.to_owned(), distance);
+        if undirected {
+            graph
+                .entry(destination.to_owned())
+                .or_default()
+                .insert(start.to_owned(), distance);
+        }
+    }
+
+    /// Builds a dense all-pairs shortest-distance matrix with Floyd-Warshall,
+    /// along with the station-name-to-index lookup used to read it. The
+    /// diagonal is seeded at infinity rather than zero: a station has no
+    /// direct zero-length route to itself, so `distance_matrix[i][i]` settles
+    /// on the shortest genuine cycle back through the graph, if any exists.
+    fn floyd_warshall(graph: &Stations) -> (HashMap<String, usize>, Vec<Vec<u32>>) {
+        const INFINITY: u32 = u32::MAX;
+
+        let mut stations: Vec<&String> = graph
+            .iter()
+            .flat_map(|(start, edges)| std::iter::once(start).chain(edges.keys()))
+            .collect::<std::collections::HashSet<_>>()
+            .into_iter()
+            .collect();
+        stations.sort_unstable();
+        let n = stations.len();
+        let index: HashMap<String, usize> = stations
+            .iter()
+            .enumerate()
+            .map(|(i, station)| ((*station).clone(), i))
+            .collect();
+
+        let mut dist = vec![vec![INFINITY; n]; n];
+        for (start, edges) in graph {
+            let i = index[start];
+            for (destination, weight) in edges {
+                let j = index[destination];
+                dist[i][j] = dist[i][j].min(*weight);
+            }
+        }
+
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    let through_k = dist[i][k].saturating_add(dist[k][j]);
+                    if through_k < dist[i][j] {
+                        dist[i][j] = through_k;
+                    }
+                }
+            }
         }
-        Self { graph }
+
+        (index, dist)
     }
 
-    pub fn eval_cmd(&self, cmd: &Command) -> String {
-        let result = match cmd {
-            Command::ABCDistance => self.route_distance(&["A", "B", "C"]),
-            Command::ADDistance => self.route_distance(&["A", "D"]),
-            Command::ADCDistance => self.route_distance(&["A", "D", "C"]),
-            Command::AEBCDDistance => self.route_distance(&["A", "E", "B", "C", "D"]),
-            Command::AEDDistance => self.route_distance(&["A", "E", "D"]),
-            Command::CCircular => self.circular_route("C", 3),
-            Command::ACFourStop => self.exact_stops("A", "B", 4),
-            Command::ACExpress => self.shortest_route("A", "C"),
-            Command::BCircle => self.shortest_route("B", "B"),
-            Command::CLessThanCircular => self.routes_less_than("C", "C", 30),
+    pub fn eval_cmd(&self, query: &Query) -> String {
+        let result = match query {
+            Query::Distance(stops) => {
+                let stops: Vec<&str> = stops.iter().map(String::as_str).collect();
+                self.route_distance(&stops)
+            }
+            Query::Shortest(start, destination) => self.shortest_route(start, destination),
+            Query::TripsExact(start, destination, stops) => {
+                self.exact_stops(start, destination, *stops)
+            }
+            Query::TripsMaxStops(start, destination, stops) => {
+                self.trips_up_to_stops(start, destination, *stops)
+            }
+            Query::TripsMaxDistance(start, destination, max_distance) => {
+                self.routes_less_than(start, destination, *max_distance)
+            }
+            Query::Circular(start, max_stops) => self.circular_route(start, *max_stops),
+            Query::Longest(start, destination, hop_limit) => {
+                self.longest_route(start, destination, *hop_limit)
+            }
+            Query::Tour(origin, closed) => self.shortest_tour(origin, *closed),
         };
         match result {
             Some(num) => num.to_string(),
@@ -124,7 +334,11 @@ impl RouteMap {
         let mut count = 0;
         while stops_made < max_stops {
             for stop in &stops {
-                let station = self.graph.get(stop.to_owned())?;
+                // A stop with no outgoing edges is simply a dead end for
+                // this branch, not a reason to abort the whole search.
+                let Some(station) = self.graph.get(stop.to_owned()) else {
+                    continue;
+                };
                 for (destination, _distance) in station {
                     if destination == start {
                         count += 1
@@ -165,38 +379,89 @@ impl RouteMap {
         Some(count)
     }
 
-    /// Finds the distance of the shortest route between two places
-    fn shortest_route(&self, start: &str, destination: &str) -> Option<u32> {
-        let mut shortest_route = Route {
-            stops: vec![],
-            distance: std::u32::MAX,
-        };
+    /// Finds the number of routes between two places with at most a given number of stops
+    fn trips_up_to_stops(&self, start: &str, destination: &str, max_stops: u32) -> Option<u32> {
+        let mut count = 0;
         let start = Route {
             stops: vec![start.to_owned()],
             distance: 0,
         };
         let mut stops = self.next_stops(start);
-        let mut next_stops = vec![];
-        while stops.len() > 0 {
+        let mut stops_made = 1;
+        while stops_made <= max_stops {
+            let mut next_stops = vec![];
             for route in stops {
-                if route.distance < shortest_route.distance {
-                    if route.current_station() == destination {
-                        shortest_route = route;
-                    } else {
-                        next_stops.extend(self.next_stops(route));
-                    }
+                if route.current_station() == destination {
+                    count += 1;
                 }
+                next_stops.extend(self.next_stops(route));
             }
             stops = next_stops;
-            next_stops = vec![];
+            stops_made += 1;
         }
-        if shortest_route.distance == std::u32::MAX {
-            None
-        } else {
-            Some(shortest_route.distance)
+        Some(count)
+    }
+
+    /// Finds the distance of the shortest route between two places by reading
+    /// the all-pairs distance matrix precomputed at construction time.
+    fn shortest_route(&self, start: &str, destination: &str) -> Option<u32> {
+        let i = *self.distance_index.get(start)?;
+        let j = *self.distance_index.get(destination)?;
+        match self.distance_matrix[i][j] {
+            u32::MAX => None,
+            distance => Some(distance),
         }
     }
 
+    /// Finds the distance of the longest simple route between two places.
+    /// A simple route never revisits a station, since otherwise a cycle on
+    /// the route would make the answer unbounded. `hop_limit`, if given,
+    /// caps recursion depth for dense graphs where the search is otherwise
+    /// exponential (longest simple path is NP-hard in general).
+    fn longest_route(&self, start: &str, destination: &str, hop_limit: Option<u32>) -> Option<u32> {
+        let mut visited: HashSet<&str> = HashSet::new();
+        visited.insert(start);
+        self.longest_route_from(start, destination, hop_limit, &mut visited)
+    }
+
+    /// Recursive step of `longest_route`. Arrival at `destination` is always
+    /// checked before consulting `visited`, so a circular query (where
+    /// `destination` is the start station, already marked visited to stop
+    /// it being reused as a pass-through) can still close the loop on its
+    /// last hop instead of being filtered out like any other revisit.
+    fn longest_route_from<'a>(
+        &'a self,
+        current: &'a str,
+        destination: &str,
+        hops_remaining: Option<u32>,
+        visited: &mut HashSet<&'a str>,
+    ) -> Option<u32> {
+        if hops_remaining == Some(0) {
+            return None;
+        }
+        let edges = self.graph.get(current)?;
+        let mut best: Option<u32> = None;
+        for (neighbor, weight) in edges {
+            let neighbor = neighbor.as_str();
+            let remaining = hops_remaining.map(|hops| hops - 1);
+            let extension = if neighbor == destination {
+                Some(0)
+            } else if visited.contains(neighbor) {
+                None
+            } else {
+                visited.insert(neighbor);
+                let result = self.longest_route_from(neighbor, destination, remaining, visited);
+                visited.remove(neighbor);
+                result
+            };
+            if let Some(rest) = extension {
+                let total = weight + rest;
+                best = Some(best.map_or(total, |current_best| current_best.max(total)));
+            }
+        }
+        best
+    }
+
     /// Finds the number of routes between two places less than a certain distance
     fn routes_less_than(&self, start: &str, destination: &str, max_distance: u32) -> Option<u32> {
         let mut count = 0;
@@ -224,7 +489,11 @@ impl RouteMap {
     /// Takes a route and returns a Vec of routes representing all possible direct routes.
     fn next_stops(&self, route: Route) -> Vec<Route> {
         let mut new_routes: Vec<Route> = vec![];
-        let station = self.graph.get(route.current_station()).unwrap();
+        // A station with no outgoing edges (a leaf, or one that only ever
+        // appears as a destination) simply has no next stops.
+        let Some(station) = self.graph.get(route.current_station()) else {
+            return new_routes;
+        };
         for (destination, distance) in station {
             let mut r = Route {
                 stops: route.stops.to_owned(),
@@ -236,31 +505,174 @@ impl RouteMap {
         }
         new_routes
     }
+
+    /// Finds the shortest tour that visits every station at least once,
+    /// starting from `origin`. When `closed` is true the tour must also
+    /// return to `origin`. Uses an exact Held-Karp dynamic program for small
+    /// station counts and falls back to a nearest-neighbor + 2-opt heuristic
+    /// beyond that, since the exact search is exponential in station count.
+    /// Returns `None` if no visiting order exists at all, e.g. because some
+    /// station can't be reached from `origin` by any path. Dead-end stations
+    /// that can be reached but can't go anywhere further (or, when `closed`,
+    /// can't return to `origin`) are fine as long as some order still works.
+    pub fn shortest_tour(&self, origin: &str, closed: bool) -> Option<u32> {
+        // `distance_index` is built by `floyd_warshall` from every station
+        // that appears anywhere in the graph, source or destination, so it
+        // covers leaf stations that `self.graph.keys()` would miss.
+        let origin_index = *self.distance_index.get(origin)?;
+        let n = self.distance_matrix.len();
+
+        let mut dist = self.distance_matrix.clone();
+        for (i, row) in dist.iter_mut().enumerate() {
+            row[i] = 0;
+        }
+
+        if n <= 12 {
+            Self::held_karp(&dist, origin_index, closed)
+        } else {
+            Self::nearest_neighbor_two_opt(&dist, origin_index, closed)
+        }
+    }
+
+    /// Exact Held-Karp DP: `dp[mask][j]` is the minimum cost to start at
+    /// `origin`, visit exactly the non-origin stations in `mask`, and end
+    /// at `j`. `mask` is encoded as a bitmask over station indices.
+    fn held_karp(dist: &[Vec<u32>], origin: usize, closed: bool) -> Option<u32> {
+        let n = dist.len();
+        if n <= 1 {
+            return Some(0);
+        }
+
+        let full_mask: usize = ((1 << n) - 1) & !(1 << origin);
+        let mut dp = vec![vec![u32::MAX; n]; 1 << n];
+        for j in 0..n {
+            if j != origin {
+                dp[1 << j][j] = dist[origin][j];
+            }
+        }
+
+        for mask in 1..(1 << n) {
+            if mask & (1 << origin) != 0 {
+                continue;
+            }
+            for j in 0..n {
+                if j == origin || mask & (1 << j) == 0 || dp[mask][j] == u32::MAX {
+                    continue;
+                }
+                let cost = dp[mask][j];
+                for k in 0..n {
+                    if k == origin || mask & (1 << k) != 0 {
+                        continue;
+                    }
+                    let next_mask = mask | (1 << k);
+                    let next_cost = cost.saturating_add(dist[j][k]);
+                    if next_cost < dp[next_mask][k] {
+                        dp[next_mask][k] = next_cost;
+                    }
+                }
+            }
+        }
+
+        (0..n)
+            .filter(|&j| j != origin)
+            .filter_map(|j| {
+                let cost = dp[full_mask][j];
+                if cost == u32::MAX {
+                    None
+                } else if closed {
+                    match dist[j][origin] {
+                        u32::MAX => None,
+                        back => Some(cost + back),
+                    }
+                } else {
+                    Some(cost)
+                }
+            })
+            .min()
+    }
+
+    /// Heuristic fallback for large station counts: builds a tour greedily by
+    /// always stepping to the nearest unvisited station, then repeatedly
+    /// reverses sub-segments (2-opt) while doing so shortens the tour.
+    fn nearest_neighbor_two_opt(dist: &[Vec<u32>], origin: usize, closed: bool) -> Option<u32> {
+        let n = dist.len();
+        let mut visited = vec![false; n];
+        let mut tour = vec![origin];
+        visited[origin] = true;
+        let mut current = origin;
+        for _ in 1..n {
+            let next = (0..n)
+                .filter(|&j| !visited[j])
+                .min_by_key(|&j| dist[current][j])
+                .expect("an unvisited station must exist");
+            visited[next] = true;
+            tour.push(next);
+            current = next;
+        }
+
+        let tour_cost = |tour: &[usize]| -> u32 {
+            let mut total: u32 = tour
+                .windows(2)
+                .fold(0, |acc, w| acc.saturating_add(dist[w[0]][w[1]]));
+            if closed {
+                total = total.saturating_add(dist[*tour.last().unwrap()][tour[0]]);
+            }
+            total
+        };
+
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for i in 1..n.saturating_sub(1) {
+                for j in (i + 1)..n {
+                    let mut candidate = tour.clone();
+                    candidate[i..=j].reverse();
+                    if tour_cost(&candidate) < tour_cost(&tour) {
+                        tour = candidate;
+                        improved = true;
+                    }
+                }
+            }
+        }
+        match tour_cost(&tour) {
+            u32::MAX => None,
+            cost => Some(cost),
+        }
+    }
 }
 
 #[test]
 fn check_distance() {
-    let routes = RouteMap::new("AB2, BC3");
+    let routes = RouteMap::parse("AB2, BC3", InputFormat::Compact, false);
     let distance = routes.route_distance(&["A", "B", "C"]).unwrap();
     assert_eq!(5, distance);
 }
 #[test]
 fn check_no_route() {
-    let routes = RouteMap::new("AB3, CD4");
+    let routes = RouteMap::parse("AB3, CD4", InputFormat::Compact, false);
     let distance = routes.route_distance(&["A", "C"]);
     assert_eq!(None, distance)
 }
 
 #[test]
 fn check_circular() {
-    let routes = RouteMap::new("CD3, DE3, EC3, EB4, BC4");
+    let routes = RouteMap::parse("CD3, DE3, EC3, EB4, BC4", InputFormat::Compact, false);
     let count = routes.circular_route("C", 4).unwrap();
     assert_eq!(2, count)
 }
 
+#[test]
+fn check_circular_through_leaf_station() {
+    // D is a leaf (no outgoing edges), so expanding through it must not
+    // abort the whole search for the genuine C -> A -> C loop.
+    let routes = RouteMap::parse("CA1, CD1, AC1", InputFormat::Compact, false);
+    let count = routes.circular_route("C", 2).unwrap();
+    assert_eq!(1, count)
+}
+
 #[test]
 fn check_exact_stops() {
-    let routes = RouteMap::new("AB3, AC2, BC3, BA2, CA7");
+    let routes = RouteMap::parse("AB3, AC2, BC3, BA2, CA7", InputFormat::Compact, false);
     println!("{:?}", routes);
     let count = routes.exact_stops("A", "B", 2).unwrap();
     assert_eq!(2, count)
@@ -268,21 +680,121 @@ fn check_exact_stops() {
 
 #[test]
 fn check_shortest_route() {
-    let routes = RouteMap::new("AB1, BC1, AD3, DC3");
+    let routes = RouteMap::parse("AB1, BC1, AD3, DC3", InputFormat::Compact, false);
     let distance = routes.shortest_route("A", "C").unwrap();
     assert_eq!(2, distance)
 }
 
+#[test]
+fn check_shortest_route_cycle_is_not_zero() {
+    let routes = RouteMap::parse("AB1, BC1, CA1", InputFormat::Compact, false);
+    let distance = routes.shortest_route("A", "A").unwrap();
+    assert_eq!(3, distance)
+}
+
+#[test]
+fn check_longest_route() {
+    let routes = RouteMap::parse("AB1, BC1, CD1, AD2", InputFormat::Compact, false);
+    let distance = routes.longest_route("A", "D", None).unwrap();
+    assert_eq!(3, distance)
+}
+
+#[test]
+fn check_longest_route_hop_limit() {
+    let routes = RouteMap::parse("AB1, BC1, CD1, AD2", InputFormat::Compact, false);
+    let distance = routes.longest_route("A", "D", Some(1)).unwrap();
+    assert_eq!(2, distance)
+}
+
+#[test]
+fn check_longest_route_no_path() {
+    let routes = RouteMap::parse("AB1, CD1", InputFormat::Compact, false);
+    assert_eq!(None, routes.longest_route("A", "D", None))
+}
+
+#[test]
+fn check_longest_route_cycle() {
+    let routes = RouteMap::parse("AB1, BC1, CA1", InputFormat::Compact, false);
+    let distance = routes.longest_route("A", "A", None).unwrap();
+    assert_eq!(3, distance)
+}
+
 #[test]
 fn check_routes_less_than() {
-    let routes = RouteMap::new("AB1, BC1, BA2, CA3, CD5, DA5");
+    let routes = RouteMap::parse("AB1, BC1, BA2, CA3, CD5, DA5", InputFormat::Compact, false);
     let count = routes.routes_less_than("A", "A", 8).unwrap();
     assert_eq!(3, count)
 }
 
 #[test]
-fn check_all_commands() {
-    let routes = RouteMap::new("AB5, BC4, CD8, DC8, DE6, AD5, CE2, EB3, AE7");
+fn check_shortest_tour_closed() {
+    let routes = RouteMap::parse("AB1, BC1, CA1", InputFormat::Compact, false);
+    let distance = routes.shortest_tour("A", true).unwrap();
+    assert_eq!(3, distance)
+}
+
+#[test]
+fn check_shortest_tour_open() {
+    let routes = RouteMap::parse("AB1, BC1, CA1", InputFormat::Compact, false);
+    let distance = routes.shortest_tour("A", false).unwrap();
+    assert_eq!(2, distance)
+}
+
+#[test]
+fn check_shortest_tour_unreachable() {
+    let routes = RouteMap::parse("AB1, CD1", InputFormat::Compact, false);
+    let distance = routes.shortest_tour("A", true);
+    assert_eq!(None, distance)
+}
+
+#[test]
+fn check_shortest_tour_visits_leaf_station() {
+    // D only ever appears as a destination, never as an edge source, so it
+    // must still be picked up by the station universe the tour has to cover.
+    let routes = RouteMap::parse("AB1, BC1, CA1, CD1", InputFormat::Compact, false);
+    let distance = routes.shortest_tour("A", false).unwrap();
+    assert_eq!(3, distance)
+}
+
+#[test]
+fn check_shortest_tour_unreachable_falls_back_to_heuristic() {
+    // 15 stations (the A..M chain plus N and Z) force the nearest-neighbor/2-opt
+    // fallback (n > 12); N and Z form a disconnected pair that origin A can never reach.
+    let routes = RouteMap::parse(
+        "AB1, BC1, CD1, DE1, EF1, FG1, GH1, HI1, IJ1, JK1, KL1, LM1, NZ1",
+        InputFormat::Compact,
+        false,
+    );
+    assert_eq!(None, routes.shortest_tour("A", false));
+}
+
+#[test]
+fn check_tour_query() {
+    let routes = RouteMap::parse("AB1, BC1, CA1", InputFormat::Compact, false);
+    assert_eq!(Query::Tour("A".to_owned(), false), Query::parse("tour A").unwrap());
+    assert_eq!(
+        Query::Tour("A".to_owned(), true),
+        Query::parse("tour A closed").unwrap()
+    );
+    assert_eq!("2", routes.eval_cmd(&Query::parse("tour A").unwrap()));
+    assert_eq!("3", routes.eval_cmd(&Query::parse("tour A closed").unwrap()));
+}
+
+#[test]
+fn check_arbitrary_queries() {
+    let routes = RouteMap::parse("AB5, BC4, CD8, DC8, DE6, AD5, CE2, EB3, AE7", InputFormat::Compact, false);
+    let queries = [
+        "dist A-B-C",
+        "dist A-D",
+        "dist A-D-C",
+        "dist A-E-B-C-D",
+        "dist A-E-D",
+        "circular C stops<=3",
+        "trips A B exact=4",
+        "shortest A C",
+        "shortest B B",
+        "trips C C maxdist=30",
+    ];
     let expected = [
         "9",
         "5",
@@ -295,8 +807,87 @@ fn check_all_commands() {
         "9",
         "7",
     ];
-    for (expected, cmd) in expected.iter().zip(COMMANDS.iter()) {
-        let result = routes.eval_cmd(cmd);
+    for (expected, query) in expected.iter().zip(queries.iter()) {
+        let result = routes.eval_cmd(&Query::parse(query).unwrap());
         assert_eq!(expected, &result)
     }
 }
+
+#[test]
+fn check_query_parse_errors() {
+    assert!(Query::parse("nonsense").is_err());
+    assert!(Query::parse("trips A B badkey=4").is_err());
+    assert!(Query::parse("circular C stops>3").is_err());
+    assert!(Query::parse("longest A B hops<3").is_err());
+}
+
+#[test]
+fn check_longest_query() {
+    let routes = RouteMap::parse("AB1, BC1, CD1, AD2", InputFormat::Compact, false);
+    assert_eq!(
+        Query::Longest("A".to_owned(), "D".to_owned(), None),
+        Query::parse("longest A D").unwrap()
+    );
+    assert_eq!(
+        Query::Longest("A".to_owned(), "D".to_owned(), Some(1)),
+        Query::parse("longest A D hops<=1").unwrap()
+    );
+    assert_eq!("3", routes.eval_cmd(&Query::parse("longest A D").unwrap()));
+}
+
+#[test]
+fn check_undirected_compact_format() {
+    let routes = RouteMap::parse("AB5", InputFormat::Compact, true);
+    assert_eq!(Some(5), routes.route_distance(&["A", "B"]));
+    assert_eq!(Some(5), routes.route_distance(&["B", "A"]));
+}
+
+#[test]
+fn check_column_format_multi_letter_stations() {
+    let input = "From To Distance\nAlpha Bravo 5\nBravo Charlie 4\n";
+    let routes = RouteMap::parse(input, InputFormat::Columns, false);
+    assert_eq!(
+        Some(9),
+        routes.route_distance(&["Alpha", "Bravo", "Charlie"])
+    );
+    assert_eq!(None, routes.route_distance(&["Charlie", "Bravo"]));
+}
+
+#[test]
+fn check_column_format_undirected() {
+    let input = "Alpha,Bravo,5\nBravo,Charlie,4\n";
+    let routes = RouteMap::parse(input, InputFormat::Columns, true);
+    assert_eq!(Some(4), routes.route_distance(&["Charlie", "Bravo"]));
+}
+
+#[test]
+fn check_detect_format() {
+    assert!(matches!(
+        InputFormat::detect("map.csv", "AB5"),
+        InputFormat::Columns
+    ));
+    assert!(matches!(
+        InputFormat::detect("map.txt", "From To Distance\nA B 5\n"),
+        InputFormat::Columns
+    ));
+    assert!(matches!(
+        InputFormat::detect("map.txt", "AB5, BC4"),
+        InputFormat::Compact
+    ));
+}
+
+#[test]
+fn check_trips_up_to_stops() {
+    let routes = RouteMap::parse("AB5, BC4, CD8, DC8, DE6, AD5, CE2, EB3, AE7", InputFormat::Compact, false);
+    let count = routes.trips_up_to_stops("A", "B", 4).unwrap();
+    assert_eq!(5, count)
+}
+
+#[test]
+fn check_trips_up_to_stops_through_leaf_station() {
+    // C has no outgoing edges, so any search that reaches it must stop
+    // cleanly there rather than panicking on a missing adjacency list.
+    let routes = RouteMap::parse("AB1, BC1", InputFormat::Compact, false);
+    let count = routes.trips_up_to_stops("A", "C", 5).unwrap();
+    assert_eq!(1, count)
+}